@@ -42,15 +42,19 @@ pub extern crate env_logger;
 extern crate log;
 
 use std::fmt;
+use std::io::{self, IsTerminal, Write as IoWrite};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::SystemTime;
+#[cfg(feature = "file-output")]
+use std::sync::{Arc, Mutex};
 use env_logger::{
     fmt::{Color, Style, StyledValue},
-    Builder,
+    Builder as EnvBuilder,
+    Target as EnvTarget,
+    WriteStyle as EnvWriteStyle,
 };
 use log::Level;
 /// TODO make this an optional feature
-use chrono::{Utc, Local};
+use chrono::{SecondsFormat, Utc, Local};
 
 /// Initializes the global logger with a pretty env logger.
 ///
@@ -70,32 +74,38 @@ pub fn init() {
 pub enum TimestampType {
     /// System time with millisecond precision
     SystemTimeMillis,
-    /// RFC 3339, local time zone
-    LocalRfc3339,
-    /// RFC 3339, UTC
-    UtcRfc3339,
+    /// RFC 3339, local time zone, at the given sub-second precision
+    LocalRfc3339(TimestampPrecision),
+    /// RFC 3339, UTC, at the given sub-second precision
+    UtcRfc3339(TimestampPrecision),
 }
 
-/// TODO don't save this as static mut (ew), do better (but without deps?)
-/// Default to system-time millis
-static mut TIMESTAMP_TYPE: TimestampType = TimestampType::SystemTimeMillis;
-
-/// Sets the timestamp type to use.
-/// Must be called before calling `init_timed()`, or it will not have any effect.
-///
-/// TODO for discussion with maintainer -->
-/// I'm declaring this as a separate function so that it doesn't require a whole additional entry
-/// path (init_timed_rfc3339() -> try_init_timed_rfc_3339() -> try_init_custom_env_rfc3339()...)
-/// but also we don't have to change any of the existing call signatures by adding arguments.
-/// But keeping this as global state feels incorrect, and I don't like that it's not call-order-safe
-/// (seems like you shouldn't be allowed to set the timestamp type after initializing a timed logger).
-/// What do you think? What's your preferred approach?
-pub fn set_timestamp_type(timestamp_type: TimestampType) {
-    unsafe {
-        TIMESTAMP_TYPE = timestamp_type;
-    }
+/// The sub-second precision to print an RFC 3339 timestamp at.
+///
+/// Mirrors env_logger's `format_timestamp_{secs,millis,micros,nanos}` family.
+/// A zero UTC offset is printed as `Z`.
+#[derive(Clone, Debug)]
+pub enum TimestampPrecision {
+    /// No sub-second digits, e.g. `2015-09-05T23:56:04Z`
+    Seconds,
+    /// Millisecond precision, e.g. `2015-09-05T23:56:04.123Z`
+    Millis,
+    /// Microsecond precision, e.g. `2015-09-05T23:56:04.123456Z`
+    Micros,
+    /// Nanosecond precision, e.g. `2015-09-05T23:56:04.123456789Z`
+    Nanos,
 }
 
+impl TimestampPrecision {
+    fn as_seconds_format(&self) -> SecondsFormat {
+        match *self {
+            TimestampPrecision::Seconds => SecondsFormat::Secs,
+            TimestampPrecision::Millis => SecondsFormat::Millis,
+            TimestampPrecision::Micros => SecondsFormat::Micros,
+            TimestampPrecision::Nanos => SecondsFormat::Nanos,
+        }
+    }
+}
 
 /// Initializes the global logger with a timed pretty env logger.
 ///
@@ -159,13 +169,7 @@ pub fn init_custom_env(environment_variable_name: &str) {
 ///
 /// This function fails to set the global logger if one has already been set.
 pub fn try_init_custom_env(environment_variable_name: &str) -> Result<(), log::SetLoggerError> {
-    let mut builder = formatted_builder();
-
-    if let Ok(s) = ::std::env::var(environment_variable_name) {
-        builder.parse_filters(&s);
-    }
-
-    builder.try_init()
+    Builder::new().parse_env(environment_variable_name).try_init()
 }
 
 /// Initialized the global logger with a timed pretty env logger, with a custom variable name.
@@ -180,85 +184,459 @@ pub fn try_init_custom_env(environment_variable_name: &str) -> Result<(), log::S
 pub fn try_init_timed_custom_env(
     environment_variable_name: &str,
 ) -> Result<(), log::SetLoggerError> {
-    let mut builder = formatted_timed_builder();
+    Builder::new()
+        .timestamp(TimestampType::SystemTimeMillis)
+        .parse_env(environment_variable_name)
+        .try_init()
+}
 
-    if let Ok(s) = ::std::env::var(environment_variable_name) {
-        builder.parse_filters(&s);
+/// Initialized the global logger with a pretty env logger, with custom variable names for
+/// both the filter directives and the color style.
+///
+/// This should be called early in the execution of a Rust program, and the
+/// global logger may only be initialized once. Future initialization attempts
+/// will return an error.
+///
+/// # Errors
+///
+/// This function fails to set the global logger if one has already been set.
+pub fn try_init_custom_env_with_style(
+    environment_variable_name: &str,
+    write_style_environment_variable_name: &str,
+) -> Result<(), log::SetLoggerError> {
+    let mut builder = Builder::new();
+    builder.parse_env(environment_variable_name);
+
+    if let Ok(s) = ::std::env::var(write_style_environment_variable_name) {
+        builder.write_style(WriteStyle::parse(&s));
     }
 
     builder.try_init()
 }
 
+/// Whether or not to print ANSI color escape codes in the formatted output.
+///
+/// Mirrors env_logger's `RUST_LOG_STYLE` values. Defaults to `Auto`, which
+/// disables colors when the destination is not a terminal, or when the
+/// [`NO_COLOR`](https://no-color.org) environment variable is set.
+#[derive(Clone, Debug, Default)]
+pub enum WriteStyle {
+    /// Colors are enabled unless the destination isn't a terminal, or `NO_COLOR` is set.
+    #[default]
+    Auto,
+    /// Colors are always enabled, regardless of the destination or `NO_COLOR`.
+    Always,
+    /// Colors are never enabled.
+    Never,
+}
+
+impl WriteStyle {
+    fn parse(s: &str) -> Self {
+        match s {
+            "always" => WriteStyle::Always,
+            "never" => WriteStyle::Never,
+            _ => WriteStyle::Auto,
+        }
+    }
+
+    fn should_colorize(&self, stream_kind: &StreamKind) -> bool {
+        match *self {
+            WriteStyle::Always => true,
+            WriteStyle::Never => false,
+            WriteStyle::Auto => {
+                if ::std::env::var_os("NO_COLOR").is_some() {
+                    return false;
+                }
+
+                match *stream_kind {
+                    StreamKind::Stdout => io::stdout().is_terminal(),
+                    StreamKind::Stderr => io::stderr().is_terminal(),
+                    StreamKind::Other => false,
+                }
+            }
+        }
+    }
+
+    /// Maps our already-resolved color decision onto `env_logger`'s own
+    /// `WriteStyle`, forcing `Always`/`Never` so that `env_logger`'s writer
+    /// agrees with what `colored_level` is about to do instead of silently
+    /// re-deriving (and overriding) its own `ColorChoice` — which, for a
+    /// `Target::Pipe`, it cannot sensibly compute itself.
+    fn to_env_write_style(use_color: bool) -> EnvWriteStyle {
+        if use_color {
+            EnvWriteStyle::Always
+        } else {
+            EnvWriteStyle::Never
+        }
+    }
+}
+
+/// Where the formatted log records should be written.
+///
+/// Forwards to [`env_logger::Target`], which this mirrors.
+///
+/// A `Pipe` is never a terminal, so [`WriteStyle::Auto`] always disables
+/// colors for it. To get colored output on a pipe anyway (e.g. a file a
+/// pager will later colorize), pair it with `.write_style(WriteStyle::Always)`:
+///
+/// ```
+/// # extern crate pretty_env_logger;
+/// # fn main() {
+/// # let writer: Box<dyn std::io::Write + Send> = Box::new(std::io::sink());
+/// pretty_env_logger::Builder::new()
+///     .target(pretty_env_logger::Target::Pipe(writer))
+///     .write_style(pretty_env_logger::WriteStyle::Always)
+///     .try_init()
+///     .ok();
+/// # }
+/// ```
+pub enum Target {
+    /// Log to `stdout`.
+    Stdout,
+    /// Log to `stderr`. This is the default.
+    Stderr,
+    /// Log to a custom pipe, such as another writer or a file.
+    Pipe(Box<dyn IoWrite + Send + 'static>),
+}
+
+/// Which standard stream (if any) the builder is configured to write to.
+///
+/// This is tracked separately from `env_logger::Target` so that `Auto`
+/// write-style detection can check the tty-ness of the right stream without
+/// needing a getter into `env_logger`'s own target.
+enum StreamKind {
+    Stdout,
+    Stderr,
+    Other,
+}
+
+/// A builder for a pretty, colored `env_logger`.
+///
+/// This mirrors `env_logger::Builder`, holding all of this crate's
+/// configuration as instance state rather than behind global statics, so
+/// construction order no longer matters and there's nothing `unsafe` about
+/// it.
+///
+/// # Example
+///
+/// ```
+/// # extern crate pretty_env_logger;
+/// # fn main() {
+/// pretty_env_logger::Builder::new()
+///     .timestamp(pretty_env_logger::TimestampType::UtcRfc3339(
+///         pretty_env_logger::TimestampPrecision::Millis,
+///     ))
+///     .parse_env("RUST_LOG")
+///     .try_init()
+///     .ok();
+/// # }
+/// ```
+pub struct Builder {
+    env_builder: EnvBuilder,
+    timestamp: Option<TimestampType>,
+    write_style: WriteStyle,
+    stream_kind: StreamKind,
+    intense_colors: bool,
+    #[cfg(feature = "file-output")]
+    tee: Tee,
+}
+
+impl Builder {
+    /// Returns a new builder with the default pretty format and no timestamp.
+    pub fn new() -> Builder {
+        Builder {
+            env_builder: EnvBuilder::new(),
+            timestamp: None,
+            write_style: WriteStyle::default(),
+            stream_kind: StreamKind::Stderr,
+            intense_colors: false,
+            #[cfg(feature = "file-output")]
+            tee: None,
+        }
+    }
+
+    /// Enables timestamps in the output, using the given format.
+    pub fn timestamp(&mut self, timestamp_type: TimestampType) -> &mut Self {
+        self.timestamp = Some(timestamp_type);
+        self
+    }
+
+    /// Sets whether ANSI color escape codes are printed in the formatted output.
+    ///
+    /// Defaults to [`WriteStyle::Auto`], which disables colors when the
+    /// destination isn't a terminal, or when `NO_COLOR` is set.
+    pub fn write_style(&mut self, write_style: WriteStyle) -> &mut Self {
+        self.write_style = write_style;
+        self
+    }
+
+    /// Sets where the formatted log records are written.
+    ///
+    /// Defaults to [`Target::Stderr`].
+    pub fn target(&mut self, target: Target) -> &mut Self {
+        let (stream_kind, env_target) = match target {
+            Target::Stdout => (StreamKind::Stdout, EnvTarget::Stdout),
+            Target::Stderr => (StreamKind::Stderr, EnvTarget::Stderr),
+            Target::Pipe(pipe) => (StreamKind::Other, EnvTarget::Pipe(pipe)),
+        };
+
+        self.stream_kind = stream_kind;
+        self.env_builder.target(env_target);
+        self
+    }
+
+    /// Sets whether level labels use the brighter, high-intensity variant of their color.
+    ///
+    /// Defaults to `false`, so existing output is unchanged.
+    pub fn intense_colors(&mut self, intense_colors: bool) -> &mut Self {
+        self.intense_colors = intense_colors;
+        self
+    }
+
+    /// Also writes a plain, uncolored copy of every record to `writer`.
+    ///
+    /// This is useful for keeping a colored terminal target while persisting
+    /// a clean, ANSI-free copy to a log file. Requires the `file-output`
+    /// feature.
+    #[cfg(feature = "file-output")]
+    pub fn tee(&mut self, writer: impl IoWrite + Send + 'static) -> &mut Self {
+        self.tee = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Parses filter directives from the given environment variable.
+    ///
+    /// This is a no-op if the variable is unset.
+    pub fn parse_env(&mut self, environment_variable_name: &str) -> &mut Self {
+        if let Ok(s) = ::std::env::var(environment_variable_name) {
+            self.env_builder.parse_filters(&s);
+        }
+        self
+    }
+
+    /// Sets the default filter level.
+    ///
+    /// Forwards to the wrapped `env_logger::Builder::filter_level`. Like
+    /// `parse_env`, it replaces any earlier default-level directive, so
+    /// whichever of the two is called last takes precedence.
+    pub fn filter_level(&mut self, filter: log::LevelFilter) -> &mut Self {
+        self.env_builder.filter_level(filter);
+        self
+    }
+
+    /// Sets the filter level for a specific module.
+    ///
+    /// Forwards to the wrapped `env_logger::Builder::filter_module`. Like
+    /// `parse_env`, it replaces any earlier directive for the same module, so
+    /// whichever of the two is called last takes precedence.
+    pub fn filter_module(&mut self, module: &str, filter: log::LevelFilter) -> &mut Self {
+        self.env_builder.filter_module(module, filter);
+        self
+    }
+
+    /// Initializes the global logger with the built pretty env logger.
+    ///
+    /// # Panics
+    ///
+    /// This method fails to set the global logger if one has already been set.
+    pub fn init(&mut self) {
+        self.try_init().unwrap();
+    }
+
+    /// Initializes the global logger with the built pretty env logger.
+    ///
+    /// # Errors
+    ///
+    /// This method fails to set the global logger if one has already been set.
+    #[cfg_attr(
+        not(feature = "file-output"),
+        allow(clippy::let_unit_value, clippy::unit_arg)
+    )]
+    pub fn try_init(&mut self) -> Result<(), log::SetLoggerError> {
+        let colors = ColorOptions {
+            use_color: self.write_style.should_colorize(&self.stream_kind),
+            intense: self.intense_colors,
+        };
+        let tee = self.tee_handle();
+
+        // Make env_logger's own writer agree with the color choice we just
+        // made — it would otherwise re-derive `ColorChoice` from its own tty
+        // check of the underlying stream, silently overriding `Always` for a
+        // piped stream or a `Target::Pipe`.
+        self.env_builder
+            .write_style(WriteStyle::to_env_write_style(colors.use_color));
+
+        match self.timestamp.clone() {
+            Some(timestamp_type) => {
+                apply_timed_format(&mut self.env_builder, timestamp_type, colors, tee)
+            }
+            None => apply_format(&mut self.env_builder, colors, tee),
+        }
+
+        self.env_builder.try_init()
+    }
+
+    #[cfg(feature = "file-output")]
+    fn tee_handle(&self) -> Tee {
+        self.tee.clone()
+    }
+
+    #[cfg(not(feature = "file-output"))]
+    fn tee_handle(&self) -> Tee {}
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
 /// Returns a `env_logger::Builder` for further customization.
 ///
 /// This method will return a colored and formatted `env_logger::Builder`
 /// for further customization. Refer to env_logger::Build crate documentation
 /// for further details and usage.
-pub fn formatted_builder() -> Builder {
-    let mut builder = Builder::new();
+#[cfg_attr(not(feature = "file-output"), allow(clippy::unit_arg))]
+pub fn formatted_builder() -> EnvBuilder {
+    let mut builder = EnvBuilder::new();
+    let colors = ColorOptions {
+        use_color: WriteStyle::default().should_colorize(&StreamKind::Stderr),
+        intense: false,
+    };
+    builder.write_style(WriteStyle::to_env_write_style(colors.use_color));
+    apply_format(&mut builder, colors, no_tee());
+    builder
+}
 
-    builder.format(|f, record| {
+/// Returns a `env_logger::Builder` for further customization.
+///
+/// This method will return a colored and time formatted `env_logger::Builder`
+/// for further customization. Refer to env_logger::Build crate documentation
+/// for further details and usage.
+#[cfg_attr(not(feature = "file-output"), allow(clippy::unit_arg))]
+pub fn formatted_timed_builder(timestamp_type: TimestampType) -> EnvBuilder {
+    let mut builder = EnvBuilder::new();
+    let colors = ColorOptions {
+        use_color: WriteStyle::default().should_colorize(&StreamKind::Stderr),
+        intense: false,
+    };
+    builder.write_style(WriteStyle::to_env_write_style(colors.use_color));
+    apply_timed_format(&mut builder, timestamp_type, colors, no_tee());
+    builder
+}
+
+/// The color behavior to apply when formatting a log record.
+#[derive(Clone, Copy)]
+struct ColorOptions {
+    use_color: bool,
+    intense: bool,
+}
+
+/// A writer that receives a parallel, uncolored copy of every log record.
+///
+/// This is a no-op unit type unless the `file-output` feature is enabled, so
+/// the default build stays dependency-light.
+#[cfg(feature = "file-output")]
+type Tee = Option<Arc<Mutex<dyn IoWrite + Send>>>;
+#[cfg(not(feature = "file-output"))]
+type Tee = ();
+
+#[cfg(feature = "file-output")]
+fn no_tee() -> Tee {
+    None
+}
+#[cfg(not(feature = "file-output"))]
+fn no_tee() -> Tee {}
+
+fn apply_format(builder: &mut EnvBuilder, colors: ColorOptions, tee: Tee) {
+    builder.format(move |f, record| {
         use std::io::Write;
 
-        let target = record.target();
-        let max_width = max_target_width(target);
+        let target_name = record.target();
+        let max_width = max_target_width(target_name);
+
+        write_tee(&tee, None, record, target_name, max_width);
 
         let mut style = f.style();
-        let level = colored_level(&mut style, record.level());
+        let level = colored_level(&mut style, record.level(), colors);
 
         let mut style = f.style();
         let target = style.set_bold(true).value(Padded {
-            value: target,
+            value: target_name,
             width: max_width,
         });
 
         writeln!(f, " {} {} > {}", level, target, record.args(),)
     });
-
-    builder
 }
 
-/// Returns a `env_logger::Builder` for further customization.
-///
-/// This method will return a colored and time formatted `env_logger::Builder`
-/// for further customization. Refer to env_logger::Build crate documentation
-/// for further details and usage.
-pub fn formatted_timed_builder() -> Builder {
-    let mut builder = Builder::new();
-
-    let timestamp_format = unsafe { TIMESTAMP_TYPE.clone() };
+fn apply_timed_format(
+    builder: &mut EnvBuilder,
+    timestamp_type: TimestampType,
+    colors: ColorOptions,
+    tee: Tee,
+) {
     builder.format(move |f, record| {
         use std::io::Write;
-        let target = record.target();
-        let max_width = max_target_width(target);
+        let target_name = record.target();
+        let max_width = max_target_width(target_name);
 
         let mut style = f.style();
-        let level = colored_level(&mut style, record.level());
+        let level = colored_level(&mut style, record.level(), colors);
 
         let mut style = f.style();
         let target = style.set_bold(true).value(Padded {
-            value: target,
+            value: target_name,
             width: max_width,
         });
 
-        // TODO statically resolve this match statement during closure construction
-        match timestamp_format {
+        match timestamp_type {
             TimestampType::SystemTimeMillis => {
                 let time = f.timestamp_millis();
+                write_tee(&tee, Some(&time.to_string()), record, target_name, max_width);
                 writeln!(f, " {} {} {} > {}", time, level, target, record.args(),)
             }
-            TimestampType::LocalRfc3339 => {
-                let time = Local::now().to_rfc3339();
+            TimestampType::LocalRfc3339(ref precision) => {
+                let time = Local::now().to_rfc3339_opts(precision.as_seconds_format(), true);
+                write_tee(&tee, Some(&time), record, target_name, max_width);
                 writeln!(f, " {} {} {} > {}", time, level, target, record.args(),)
             }
-            TimestampType::UtcRfc3339 => {
-                let time = Utc::now().to_rfc3339();
+            TimestampType::UtcRfc3339(ref precision) => {
+                let time = Utc::now().to_rfc3339_opts(precision.as_seconds_format(), true);
+                write_tee(&tee, Some(&time), record, target_name, max_width);
                 writeln!(f, " {} {} {} > {}", time, level, target, record.args(),)
             }
         }
     });
+}
 
-    builder
+/// Writes a plain, uncolored copy of `record` to the tee writer, if one is configured.
+#[cfg(feature = "file-output")]
+fn write_tee(tee: &Tee, timestamp: Option<&str>, record: &log::Record, target: &str, max_width: usize) {
+    let tee = match tee {
+        Some(tee) => tee,
+        None => return,
+    };
+    let mut writer = match tee.lock() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    let level = level_str(record.level());
+    let target = Padded {
+        value: target,
+        width: max_width,
+    };
+
+    let _ = match timestamp {
+        Some(timestamp) => writeln!(writer, " {} {} {} > {}", timestamp, level, target, record.args()),
+        None => writeln!(writer, " {} {} > {}", level, target, record.args()),
+    };
+}
+
+/// No-op when the `file-output` feature is disabled: there is no tee writer to write to.
+#[cfg(not(feature = "file-output"))]
+fn write_tee(_tee: &Tee, _timestamp: Option<&str>, _record: &log::Record, _target: &str, _max_width: usize) {
 }
 
 struct Padded<T> {
@@ -284,7 +662,29 @@ fn max_target_width(target: &str) -> usize {
     }
 }
 
-fn colored_level<'a>(style: &'a mut Style, level: Level) -> StyledValue<'a, &'static str> {
+fn level_str(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRACE",
+        Level::Debug => "DEBUG",
+        Level::Info => "INFO ",
+        Level::Warn => "WARN ",
+        Level::Error => "ERROR",
+    }
+}
+
+fn colored_level<'a>(
+    style: &'a mut Style,
+    level: Level,
+    colors: ColorOptions,
+) -> StyledValue<'a, &'static str> {
+    if !colors.use_color {
+        return style.value(level_str(level));
+    }
+
+    if colors.intense {
+        style.set_intense(true);
+    }
+
     match level {
         Level::Trace => style.set_color(Color::Magenta).value("TRACE"),
         Level::Debug => style.set_color(Color::Blue).value("DEBUG"),